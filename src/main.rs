@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{exit, Command, Stdio};
@@ -9,6 +9,12 @@ use std::ffi::OsStr;
 #[cfg(unix)]
 use std::os::unix::process::ExitStatusExt;
 
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
 #[cfg(windows)]
 use std::os::windows::process::ExitStatusExt;
 
@@ -28,6 +34,9 @@ use tempfile::{Builder as TempFileBuilder, PersistError};
 /// this can be changed.
 ///
 /// If the command exits with a nonzero exit code, the target file is *not* overwritten.
+///
+/// Multiple files can be given, in which case the command is run once per file; each file is
+/// rewritten (or skipped, on failure) independently of the others.
 #[derive(Debug, Clone, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 struct Opt {
@@ -63,6 +72,25 @@ struct Opt {
     #[structopt(short = "D", long)]
     drop_root: bool,
 
+    /// Set the output file's permissions to this octal mode (e.g. 644),
+    /// instead of restoring the original file's permissions.
+    ///
+    /// The scratch file is created with this mode up front, so the command
+    /// sees it too. Only meaningful on Unix.
+    #[structopt(long, parse(try_from_str = "parse_octal_mode"))]
+    mode: Option<u32>,
+
+    /// Don't verify that the scratch directory (and its ancestors) are safe
+    /// to write into before creating the scratch file.
+    ///
+    /// By default, rewrite works by creating a temp file in the target
+    /// directory and renaming it over the target; if that directory (or any
+    /// ancestor up to its root) is group- or world-writable, or owned by
+    /// another user, a local attacker could race the rename or pre-seed the
+    /// temp name. This flag skips that check.
+    #[structopt(long)]
+    no_check_permissions: bool,
+
     /// Read from stdin instead of the file.
     ///
     /// Instead of piping the file into the command, forward stdin to it. Use
@@ -77,9 +105,15 @@ struct Opt {
 
     // TODO: verbose mode
 
-    /// The file to rewrite
-    #[structopt(parse(from_os_str))]
-    rewrite_path: PathBuf,
+    /// The file(s) to rewrite
+    ///
+    /// Each file is processed independently, with its own scratch file and
+    /// its own REWRITE_* environment, in a single invocation of rewrite; the
+    /// command is run once per file.
+    /// A failure on one file (a nonzero exit, a signal, or any other error)
+    /// skips only that file's write; the rest are still processed.
+    #[structopt(required = true, min_values = 1, parse(from_os_str))]
+    rewrite_paths: Vec<PathBuf>,
 
     /// The subcommand to run.
     ///
@@ -89,6 +123,11 @@ struct Opt {
     command: Vec<String>,
 }
 
+/// Parse a `--mode` argument as an octal permission mode, e.g. `644` -> `0o644`.
+fn parse_octal_mode(s: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(s, 8)
+}
+
 trait ExitStatusSignal {
     fn exit_signal(&self) -> Option<i32>;
 }
@@ -118,11 +157,221 @@ enum RewriteError<'a> {
     NoSudoUser(env::VarError),
     GetPermissions(io::Error),
     SetPermissions(io::Error),
+    #[cfg(unix)]
+    GetOwnership(io::Error),
+    #[cfg(unix)]
+    SetOwnership(io::Error),
+    #[cfg(unix)]
+    LinkAnonymous(io::Error),
+    #[cfg(unix)]
+    InsecureDir {
+        path: PathBuf,
+        problem: InsecureDirProblem,
+    },
+    #[cfg(not(unix))]
+    UnsupportedMode,
+}
+
+/// Why [`check_dir_is_secure`] rejected a directory in the path from the
+/// scratch directory up to the filesystem root.
+#[cfg(unix)]
+#[derive(Debug)]
+enum InsecureDirProblem {
+    /// The directory is owned by someone other than the current user or
+    /// root, or is group- or world-writable without the sticky bit set, so
+    /// another local user could race the rename or pre-seed the
+    /// scratch file's name.
+    BadPermissions,
+    /// The directory's metadata couldn't even be read.
+    CouldNotStat(io::Error),
+}
+
+/// Verify that `dir` and every ancestor up to the filesystem root is trusted:
+/// owned by the current user or by root, and not writable by anyone else.
+/// This mirrors the checks fs-mistrust performs — directories like `/`,
+/// `/home`, or `/usr` are root-owned but not group/other-writable, so they're
+/// trusted; `/tmp` is world-writable but carries the sticky bit, so only the
+/// owner of an entry within it can remove/rename that entry, and it's
+/// trusted too. This protects the create-scratch-file/rename pattern this
+/// tool relies on: if an untrusted party can write into any directory along
+/// that path, they can race or pre-seed the rename.
+#[cfg(unix)]
+fn check_dir_is_secure<'a>(dir: &Path) -> Result<(), RewriteError<'a>> {
+    let current_uid = rustix::process::getuid().as_raw();
+
+    // `dir` is frequently relative (a bare filename's parent is "", a
+    // relative --dir/sibling path is relative too), and `Path::ancestors()`
+    // on a relative path bottoms out at "", which doesn't exist. `path.parent()`
+    // on a bare filename gives exactly that empty path, meaning "this
+    // directory", so normalize it to "." before canonicalizing so every
+    // ancestor we check is an absolute, statable path.
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+    let absolute = dir.canonicalize().map_err(|err| RewriteError::InsecureDir {
+        path: dir.to_owned(),
+        problem: InsecureDirProblem::CouldNotStat(err),
+    })?;
+
+    for ancestor in absolute.ancestors() {
+        let metadata = std::fs::symlink_metadata(ancestor).map_err(|err| RewriteError::InsecureDir {
+            path: ancestor.to_owned(),
+            problem: InsecureDirProblem::CouldNotStat(err),
+        })?;
+
+        let mode = metadata.mode();
+        let owned_by_untrusted_party = metadata.uid() != current_uid && metadata.uid() != 0;
+        // The sticky bit restricts renaming/deleting entries to their owner
+        // (or root) even when the directory itself is group- or
+        // world-writable, which is exactly what makes shared, writable
+        // directories like `/tmp` safe to use despite their mode.
+        let has_sticky_bit = mode & 0o1000 != 0;
+        let writable_by_others = mode & 0o022 != 0 && !has_sticky_bit;
+
+        if owned_by_untrusted_party || writable_by_others {
+            return Err(RewriteError::InsecureDir {
+                path: ancestor.to_owned(),
+                problem: InsecureDirProblem::BadPermissions,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// A scratch file that will eventually become the target file.
+///
+/// On Unix, we prefer an anonymous `O_TMPFILE` that is never visible under
+/// any name in the target directory; if the command is killed before we're
+/// done, it leaves nothing behind. Materializing it is a `linkat` of
+/// `/proc/self/fd/N` into a hidden unique name, followed by an atomic
+/// `rename` over the target. If `O_TMPFILE` or `/proc` isn't available (old
+/// kernels, non-procfs filesystems), we fall back to the original
+/// named-temp-file-then-`persist` approach.
+enum ScratchFile {
+    Named(tempfile::NamedTempFile),
+    #[cfg(unix)]
+    Anonymous { file: File, dir: PathBuf },
+}
+
+impl ScratchFile {
+    #[cfg(unix)]
+    fn create_anonymous_in(dir: &Path, mode: Option<u32>) -> io::Result<Self> {
+        use rustix::fs::{Mode, OFlags};
+
+        // Materializing the file later links it via procfs, so without
+        // /proc report failure here and let the caller use a named file.
+        if !Path::new("/proc/self/fd").is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "/proc/self/fd is not available"));
+        }
+
+        let fd = rustix::fs::open(
+            dir,
+            OFlags::WRONLY | OFlags::TMPFILE | OFlags::CLOEXEC,
+            Mode::from(mode.unwrap_or(0o600)),
+        )?;
+
+        Ok(ScratchFile::Anonymous {
+            file: File::from(fd),
+            dir: dir.to_owned(),
+        })
+    }
+
+    fn create_named_in<'a>(
+        filename: &OsStr,
+        dir: &'a Path,
+        #[cfg_attr(not(unix), allow(unused_variables))] mode: Option<u32>,
+    ) -> Result<Self, RewriteError<'a>> {
+        let suffix = format!("-{}", filename.to_string_lossy());
+        let mut builder = TempFileBuilder::new();
+        builder.prefix(".rewrite-tmp-").suffix(suffix.as_str());
+
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            builder.permissions(std::fs::Permissions::from_mode(mode));
+        }
+
+        builder
+            .tempfile_in(dir)
+            .map(ScratchFile::Named)
+            .map_err(|err| RewriteError::CreateTemp { dir, err })
+    }
+
+    /// The path to pass to the child (and to report via `REWRITE_TEMPFILE`).
+    /// An anonymous scratch file has no name, so we point at its procfs path
+    /// under *our own* pid, not "self" — the child is the one that reads and
+    /// opens this path, and by then "self" would resolve to the child, whose
+    /// fd table doesn't have our scratch file open at that number (it was
+    /// opened `CLOEXEC`). Our own pid stays valid for the child to open
+    /// since we're alive and waiting on it via `command.status()`.
+    fn path_for_env(&self) -> Cow<'_, Path> {
+        match self {
+            ScratchFile::Named(file) => Cow::Borrowed(file.path()),
+            #[cfg(unix)]
+            ScratchFile::Anonymous { file, .. } => {
+                use std::os::unix::io::AsRawFd;
+                Cow::Owned(PathBuf::from(format!(
+                    "/proc/{}/fd/{}",
+                    std::process::id(),
+                    file.as_raw_fd()
+                )))
+            }
+        }
+    }
+
+    fn try_clone_for_child(&self) -> io::Result<File> {
+        match self {
+            ScratchFile::Named(file) => file.as_file().try_clone(),
+            #[cfg(unix)]
+            ScratchFile::Anonymous { file, .. } => file.try_clone(),
+        }
+    }
+
+    /// Materialize the scratch file at `target`, atomically replacing
+    /// whatever is there. Returns a handle to the now-persisted file.
+    fn persist<'a>(self, target: &'a Path) -> Result<File, RewriteError<'a>> {
+        match self {
+            ScratchFile::Named(file) => file.persist(target).map_err(RewriteError::Persist),
+            #[cfg(unix)]
+            ScratchFile::Anonymous { file, dir } => {
+                link_anonymous_file(&file, &dir, target).map_err(RewriteError::LinkAnonymous)?;
+                Ok(file)
+            }
+        }
+    }
 }
 
-fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>> {
-    let path = &opt.rewrite_path;
+/// Materialize an anonymous (`O_TMPFILE`) file by linking it to a hidden,
+/// unique name in `dir` via its procfs path, then atomically renaming that
+/// hidden name over `target`. `linkat` into an existing name fails with
+/// `EEXIST`, which is why this can't link directly onto `target`.
+#[cfg(unix)]
+fn link_anonymous_file(file: &File, dir: &Path, target: &Path) -> io::Result<()> {
+    use rustix::fs::{linkat, AtFlags, CWD};
+    use std::os::unix::io::AsRawFd;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let proc_path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+
+    let unique = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let hidden_path = dir.join(format!(".rewrite-tmp-{}-{}", std::process::id(), unique));
 
+    linkat(CWD, &proc_path, CWD, &hidden_path, AtFlags::SYMLINK_FOLLOW)?;
+
+    std::fs::rename(&hidden_path, target).map_err(|err| {
+        let _ = std::fs::remove_file(&hidden_path);
+        err
+    })
+}
+
+fn run<'a>(
+    path: &'a Path,
+    sys_temp_dir: &'a Path,
+    rewrite_tmpdir: Option<&'a Path>,
+    opt: &'a Opt,
+) -> Result<i32, RewriteError<'a>> {
     // Note that we technically don't need the file to be writeable– rewrite works
     // fine if the file is read only but the directory is writeable– but we don't
     // want to edit read-only files as a courtesy to the user.
@@ -143,16 +392,32 @@ fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>
         .open(path)
         .map_err(RewriteError::Open)?;
 
-    let file_permissions = file
-        .metadata()
-        .map_err(RewriteError::GetPermissions)?
-        .permissions();
+    #[cfg(not(unix))]
+    if opt.mode.is_some() {
+        return Err(RewriteError::UnsupportedMode);
+    }
+
+    let file_metadata = file.metadata().map_err(RewriteError::GetPermissions)?;
+    let file_permissions = file_metadata.permissions();
 
-    // Get the desired directory
+    // Captured so that, on Unix, we can restore the original owner after the
+    // atomic replace; a plain rename(2)/persist leaves the new file owned by
+    // whoever ran the command, which is wrong under `sudo rewrite`.
+    #[cfg(unix)]
+    let (file_uid, file_gid) = (file_metadata.uid(), file_metadata.gid());
+
+    // Get the desired directory. When none of --temp-dir/--dir/--sibling-dir
+    // is explicitly given, REWRITE_TMPDIR stands in for the usual sibling
+    // default, for environments where passing --dir every time is impractical.
     let dir_path = if opt.temp_dir {
         sys_temp_dir
     } else if let Some(ref dir) = opt.dir {
         dir
+    } else if opt.sibling_dir {
+        path.parent()
+            .expect("Target file doesn't have a parent directory?")
+    } else if let Some(tmpdir) = rewrite_tmpdir {
+        tmpdir
     } else {
         path.parent()
             .expect("Target file doesn't have a parent directory?")
@@ -161,12 +426,18 @@ fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>
     // This panic shouldn't happen because the file would have failed to open
     let filename = path.file_name().expect("Target file doesn't have a name?");
 
-    // Attach the filename as a suffix so that we can tell what file this is scratch for
-    let scratch_file = TempFileBuilder::new()
-        .prefix(".rewrite-tmp-")
-        .suffix(format!("-{}", filename.to_string_lossy()).as_str())
-        .tempfile_in(dir_path)
-        .map_err(|err| RewriteError::CreateTemp { dir: dir_path, err })?;
+    #[cfg(unix)]
+    if !opt.no_check_permissions {
+        check_dir_is_secure(dir_path)?;
+    }
+
+    // Prefer an anonymous O_TMPFILE (never visible under any name) and fall
+    // back to the named-temp-file approach if the platform can't do it.
+    #[cfg(unix)]
+    let scratch_file = ScratchFile::create_anonymous_in(dir_path, opt.mode)
+        .or_else(|_| ScratchFile::create_named_in(filename, dir_path, opt.mode))?;
+    #[cfg(not(unix))]
+    let scratch_file = ScratchFile::create_named_in(filename, dir_path, None)?;
 
     // We can't pass a NamedTempFile to a subprocess, so we attempt to duplicate
     // the file descriptor and create a `File`.
@@ -175,8 +446,7 @@ fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>
     // There is an open issue on github to allow the file to be destructured:
     // https://github.com/Stebalien/tempfile/issues/60
     let scratch_file_for_child = scratch_file
-        .as_file()
-        .try_clone()
+        .try_clone_for_child()
         .map_err(RewriteError::DupTemp)?;
 
     // Build the command string. We use sudo to drop priveleges and sh for shell mode.
@@ -213,7 +483,7 @@ fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>
 
     // Attach environment
     if !opt.no_env {
-        command.env("REWRITE_TEMPFILE", scratch_file.path());
+        command.env("REWRITE_TEMPFILE", scratch_file.path_for_env().as_os_str());
         command.env("REWRITE_OUTPUT", path);
         command.env("REWRITE_INPUT", if opt.stdin { OsStr::new("-") } else { path.as_os_str() });
     }
@@ -241,51 +511,162 @@ fn run<'a>(sys_temp_dir: &'a Path, opt: &'a Opt) -> Result<i32, RewriteError<'a>
     // If all went well, and we're not in no-op mode, replace the original file
     // with the temporary file.
     if !opt.no_op {
-        scratch_file
-            .persist(path)
-            .map_err(RewriteError::Persist)?
-            .set_permissions(file_permissions)
+        let persisted_file = scratch_file.persist(path)?;
+
+        // On Unix, restore the original owner before restoring permissions: chown(2)
+        // typically clears the setuid/setgid bits, so it has to happen first.
+        #[cfg(unix)]
+        {
+            let current_owner = persisted_file
+                .metadata()
+                .map_err(RewriteError::GetOwnership)?;
+
+            if current_owner.uid() != file_uid || current_owner.gid() != file_gid {
+                // SAFETY: file_uid/file_gid came straight from the target
+                // file's own metadata, so they're valid ids on this system.
+                let (uid, gid) = unsafe {
+                    (
+                        rustix::fs::Uid::from_raw(file_uid),
+                        rustix::fs::Gid::from_raw(file_gid),
+                    )
+                };
+
+                // Use the already-open descriptor rather than re-resolving
+                // `path` by name, which would reopen exactly the TOCTOU
+                // window the atomic rename is meant to close (e.g. a symlink
+                // dropped in at `path` between the rename and here).
+                rustix::fs::fchown(&persisted_file, Some(uid), Some(gid))
+                    .map_err(|err| RewriteError::SetOwnership(err.into()))?;
+            }
+        }
+
+        // --mode overrides the default behavior of restoring the original
+        // file's permissions.
+        #[cfg(unix)]
+        let final_permissions = opt
+            .mode
+            .map(std::fs::Permissions::from_mode)
+            .unwrap_or(file_permissions);
+        #[cfg(not(unix))]
+        let final_permissions = file_permissions;
+
+        persisted_file
+            .set_permissions(final_permissions)
             .map_err(RewriteError::SetPermissions)?;
     }
 
     Ok(0)
 }
 
-fn main() {
+/// Resolve the `REWRITE_TMPDIR` environment variable, if set, as the
+/// fallback scratch directory for environments (sandboxes, containers)
+/// where the system temp dir may be unwritable or absent and passing
+/// `--dir` every invocation is impractical. Validated eagerly, rather than
+/// letting a bad value surface deep inside `CreateTemp` — but only when an
+/// explicit --dir/--temp-dir/--sibling-dir hasn't already decided the
+/// scratch directory, since then REWRITE_TMPDIR is never consulted anyway
+/// and a stale/invalid value in the environment shouldn't break invocations
+/// that don't depend on it.
+fn resolve_rewrite_tmpdir(opt: &Opt) -> Option<PathBuf> {
+    if opt.temp_dir || opt.dir.is_some() || opt.sibling_dir {
+        return None;
+    }
+
+    let dir = PathBuf::from(env::var_os("REWRITE_TMPDIR")?);
+
+    if let Err(err) = TempFileBuilder::new().tempfile_in(&dir) {
+        eprintln!(
+            "REWRITE_TMPDIR ('{}') isn't usable as a scratch directory: {}",
+            dir.display(),
+            err
+        );
+        exit(1);
+    }
+
+    Some(dir)
+}
+
+/// Report a single file's [`RewriteError`], prefixed with the file's path
+/// so failures are attributable when rewriting several files at once.
+fn report_error(path: &Path, err: RewriteError) {
     use crate::RewriteError::*;
 
+    match err {
+        Open(err) => eprintln!("{}: Error opening for read: {}", path.display(), err),
+        CreateTemp { dir, err } => eprintln!(
+            "{}: Error creating temporary file in '{}': {}",
+            path.display(),
+            dir.display(),
+            err
+        ),
+        DupTemp(err) => eprintln!("{}: Error creating duplicate file descriptor: {}", path.display(), err),
+        SpawnChild(err) => eprintln!("{}: Error spawning command: {}", path.display(), err),
+        Signal(None) => eprintln!("{}: Command terminated from unknown signal", path.display()),
+        Signal(Some(sig)) => eprintln!("{}: Command terminated from signal {}", path.display(), sig),
+        Persist(err) => eprintln!("{}: Error persisting temporary file: {}", path.display(), err),
+        NoSudoUser(err) => eprintln!("{}: --drop-root was given, but there was an error reading SUDO_USER: {}", path.display(), err),
+        GetPermissions(err) => eprintln!("{}: Error getting file permissions: {}", path.display(), err),
+        SetPermissions(err) => eprintln!("{}: command completed successfully, but error restoring file permissions to the new file: {}", path.display(), err),
+        #[cfg(unix)]
+        GetOwnership(err) => eprintln!("{}: command completed successfully, but error reading ownership of the new file: {}", path.display(), err),
+        #[cfg(unix)]
+        SetOwnership(err) => eprintln!("{}: command completed successfully, but error restoring file ownership to the new file: {}", path.display(), err),
+        #[cfg(unix)]
+        LinkAnonymous(err) => eprintln!("{}: Error persisting anonymous temporary file: {}", path.display(), err),
+        #[cfg(unix)]
+        InsecureDir { path: dir, problem: InsecureDirProblem::BadPermissions } => eprintln!(
+            "{}: Refusing to rewrite through insecure directory '{}': group/world-writable or not owned by the current user (use --no-check-permissions to skip this check)",
+            path.display(),
+            dir.display()
+        ),
+        #[cfg(unix)]
+        InsecureDir { path: dir, problem: InsecureDirProblem::CouldNotStat(err) } => eprintln!(
+            "{}: Error checking permissions of directory '{}': {}",
+            path.display(),
+            dir.display(),
+            err
+        ),
+        #[cfg(not(unix))]
+        UnsupportedMode => eprintln!("{}: --mode is only supported on Unix platforms", path.display()),
+    }
+}
+
+fn main() {
     let opt = Opt::from_args();
-    let path = &opt.rewrite_path;
-    let sys_temp_dir = env::temp_dir();
 
-    let result = run(&sys_temp_dir, &opt);
+    // --stdin forwards the single stdin stream straight to the child; with
+    // more than one file, the first file's command would consume all of it
+    // and every subsequent file's command would see immediate EOF, silently
+    // overwriting those files with near-empty output.
+    if opt.stdin && opt.rewrite_paths.len() > 1 {
+        eprintln!("--stdin is only supported with a single file");
+        exit(1);
+    }
 
-    let code = match result {
-        Ok(0) => 0,
-        Ok(code) => {
-            eprintln!("Command exited with status code {}; skipping write", code);
-            code
-        }
-        Err(err) => {
-            match err {
-                Open(err) => eprintln!("Error opening '{}' for read: {}", path.display(), err),
-                CreateTemp { dir, err } => eprintln!(
-                    "Error creating temporary file in '{}': {}",
-                    dir.display(),
-                    err
-                ),
-                DupTemp(err) => eprintln!("Error creating duplicate file descriptor: {}", err),
-                SpawnChild(err) => eprintln!("Error spawning command: {}", err),
-                Signal(None) => eprintln!("Command terminated from unknown signal"),
-                Signal(Some(sig)) => eprintln!("Command terminated from signal {}", sig),
-                Persist(err) => eprintln!("Error persisting temporary file: {}", err),
-                NoSudoUser(err) => eprintln!("--drop-priveleges was given, but there was an error reading SUDO_USER: {}", err),
-                GetPermissions(err) => eprintln!("Error getting file permissions for {}: {}", path.display(), err),
-                SetPermissions(err) => eprintln!("command completed successfully, but error restoring file permissions to the new file: {}", err),
+    let sys_temp_dir = env::temp_dir();
+    let rewrite_tmpdir = resolve_rewrite_tmpdir(&opt);
+
+    // Each file is rewritten independently; one file's failure doesn't stop
+    // the others, but the process exits nonzero if any of them failed.
+    let mut any_failed = false;
+
+    for path in &opt.rewrite_paths {
+        match run(path, &sys_temp_dir, rewrite_tmpdir.as_deref(), &opt) {
+            Ok(0) => {}
+            Ok(code) => {
+                eprintln!(
+                    "{}: Command exited with status code {}; skipping write",
+                    path.display(),
+                    code
+                );
+                any_failed = true;
+            }
+            Err(err) => {
+                report_error(path, err);
+                any_failed = true;
             }
-            1
         }
-    };
+    }
 
-    exit(code);
+    exit(any_failed as i32);
 }